@@ -0,0 +1,101 @@
+use std::ptr::NonNull;
+
+use anyhow::{anyhow, Result};
+use io_uring::{types, IoUring};
+
+/// A provided-buffer ring registered with `IORING_REGISTER_PBUF_RING`.
+///
+/// Backs multishot recv ops: instead of the application posting one SQE per
+/// buffer up front, the kernel pulls buffers out of this ring as datagrams
+/// arrive and tags each CQE with the buffer id it used, so buffers only need
+/// to be handed back (via [`BufRing::recycle`]) once their payload has
+/// actually been consumed.
+pub struct BufRing {
+    ring: NonNull<types::BufRingEntry>,
+    bgid: u16,
+    mask: u16,
+    buf_len: usize,
+    bufs: Vec<Box<[u8]>>,
+    tail: u16,
+}
+
+impl BufRing {
+    pub fn new(io_uring: &IoUring, entries: u16, bgid: u16, buf_len: usize) -> Result<Self> {
+        assert!(entries.is_power_of_two(), "buffer ring size must be a power of two");
+
+        let layout = std::alloc::Layout::array::<types::BufRingEntry>(entries as usize)?
+            .align_to(4096)?;
+        let raw = unsafe { std::alloc::alloc_zeroed(layout) } as *mut types::BufRingEntry;
+        let ring = NonNull::new(raw).ok_or_else(|| anyhow!("failed to allocate buffer ring"))?;
+
+        unsafe {
+            io_uring
+                .submitter()
+                .register_buf_ring(ring.as_ptr() as u64, entries, bgid)?;
+        }
+
+        let mut buf_ring = BufRing {
+            ring,
+            bgid,
+            mask: entries - 1,
+            buf_len,
+            bufs: (0..entries)
+                .map(|_| vec![0u8; buf_len].into_boxed_slice())
+                .collect(),
+            tail: 0,
+        };
+
+        for bid in 0..entries {
+            buf_ring.stage(bid, bid);
+        }
+        buf_ring.publish(entries);
+
+        Ok(buf_ring)
+    }
+
+    pub fn bgid(&self) -> u16 {
+        self.bgid
+    }
+
+    /// Buffer `bid`'s payload, truncated to the `len` bytes the matching CQE
+    /// reported.
+    pub fn payload(&self, bid: u16, len: usize) -> &[u8] {
+        &self.bufs[bid as usize][..len]
+    }
+
+    /// Mutable version of [`BufRing::payload`], for callers (like quiche)
+    /// that need to mutate a datagram in place while parsing it.
+    pub fn payload_mut(&mut self, bid: u16, len: usize) -> &mut [u8] {
+        &mut self.bufs[bid as usize][..len]
+    }
+
+    /// Hand buffer `bid` back to the kernel so a future completion can reuse
+    /// it. Must be called once the caller is done reading its payload.
+    pub fn recycle(&mut self, bid: u16) {
+        self.stage(0, bid);
+        self.publish(1);
+    }
+
+    /// Write buffer `bid`'s address/length into the ring slot `offset`
+    /// positions past the current (not-yet-published) tail -- `0` for a
+    /// single `recycle`, or `0..entries` for the bulk fill in `new()`. Does
+    /// not publish the slot to the kernel yet; callers do that via
+    /// `publish`, which lets the bulk fill in `new()` bump the shared tail
+    /// only once. `recycle` publishes each buffer as it stages it.
+    fn stage(&mut self, offset: u16, bid: u16) {
+        let idx = (self.tail.wrapping_add(offset)) & self.mask;
+        unsafe {
+            let entry = self.ring.as_ptr().add(idx as usize);
+            (*entry).set_addr(self.bufs[bid as usize].as_ptr() as u64);
+            (*entry).set_len(self.buf_len as u32);
+            (*entry).set_bid(bid);
+        }
+    }
+
+    fn publish(&mut self, count: u16) {
+        self.tail = self.tail.wrapping_add(count);
+        unsafe {
+            types::BufRingEntry::set_tail(self.ring.as_ptr(), self.tail);
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! A small ringbahn/asyncio-style async layer on top of the raw ring: ops
+//! become `Future`s that resolve with their CQE's result, and the ring loop
+//! only has to route `TAG`ged CQEs back here. `do_work`'s multishot recv still drives the
+//! ring directly -- a multishot op yields many CQEs per SQE, which doesn't
+//! fit a future that resolves once -- but one-shot ops like the QUIC
+//! server's outgoing `SendMsg`s are a perfect match, and go through here.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Wake, Waker};
+
+use io_uring::{opcode, squeue::Entry, types};
+
+/// Every `user_data` the executor hands out has this bit set, so the driver
+/// loop can tell an executor-owned op apart from the ring's other raw SQEs
+/// (e.g. `do_work`'s multishot `RecvMsgMulti`, tagged `0`) with a single
+/// branch before deciding who should see a given CQE.
+pub const TAG: u64 = 1 << 63;
+
+/// The lifecycle of one in-flight SQE, addressed by its `user_data`.
+enum State {
+    /// Registered but not yet handed to the submission queue.
+    Empty,
+    /// Submitted; parked on `Waker` until its CQE arrives.
+    Submitted(Waker),
+    /// The CQE arrived; this is `cqe.result()`.
+    Completed(io::Result<i32>),
+    /// Consumed by `Submission::poll` returning `Ready`; nothing should
+    /// touch this slot again. A `Submission` dropped before completion
+    /// removes its slot instead of marking it `Cancelled`.
+    Cancelled,
+}
+
+struct Inner {
+    next_user_data: u64,
+    completions: HashMap<u64, State>,
+    to_submit: VecDeque<Entry>,
+    tasks: Vec<Option<Pin<Box<dyn Future<Output = ()>>>>>,
+    ready: VecDeque<usize>,
+    /// Indices of `tasks` left behind by finished futures, so a listener
+    /// that runs for a long time reuses those slots instead of growing
+    /// `tasks` for the life of the process.
+    free_tasks: Vec<usize>,
+}
+
+/// Owns every in-flight op and spawned task for one ring. Cheap to clone --
+/// clones share the same state, the way `Rc<RefCell<_>>` always do -- so a
+/// spawned task can hold its own `Driver` handle to submit further ops.
+#[derive(Clone)]
+pub struct Driver {
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl Driver {
+    pub fn new() -> Self {
+        Driver {
+            inner: Rc::new(RefCell::new(Inner {
+                next_user_data: 0,
+                completions: HashMap::new(),
+                to_submit: VecDeque::new(),
+                tasks: Vec::new(),
+                ready: VecDeque::new(),
+                free_tasks: Vec::new(),
+            })),
+        }
+    }
+
+    /// Spawn a fire-and-forget task (e.g. "drive this `SendMsg` to
+    /// completion and log if it fails"). Queued as ready, so the next
+    /// `run_ready` polls it and it can perform its first submission without
+    /// waiting for a wake.
+    pub fn spawn(&self, fut: impl Future<Output = ()> + 'static) {
+        let mut inner = self.inner.borrow_mut();
+        let index = match inner.free_tasks.pop() {
+            Some(index) => {
+                inner.tasks[index] = Some(Box::pin(fut));
+                index
+            }
+            None => {
+                let index = inner.tasks.len();
+                inner.tasks.push(Some(Box::pin(fut)));
+                index
+            }
+        };
+        inner.ready.push_back(index);
+    }
+
+    /// Drain every SQE queued by futures that have been polled since the
+    /// last call. The caller (`do_work`'s ring loop) is responsible for
+    /// actually pushing these onto the submission queue.
+    pub fn drain_submissions(&self) -> Vec<Entry> {
+        self.inner.borrow_mut().to_submit.drain(..).collect()
+    }
+
+    /// Feed one CQE back in: mark its `State` as `Completed`, which wakes
+    /// whichever task awaited it and queues that task for re-polling.
+    pub fn complete(&self, user_data: u64, result: io::Result<i32>) {
+        let waker = {
+            let mut inner = self.inner.borrow_mut();
+            match inner.completions.insert(user_data, State::Completed(result)) {
+                Some(State::Submitted(waker)) => Some(waker),
+                _ => None,
+            }
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Re-poll every task woken since the last call (including freshly
+    /// spawned ones), dropping any that finished.
+    pub fn run_ready(&self) {
+        loop {
+            let index = match self.inner.borrow_mut().ready.pop_front() {
+                Some(index) => index,
+                None => break,
+            };
+
+            let mut fut = match self.inner.borrow_mut().tasks[index].take() {
+                Some(fut) => fut,
+                None => continue,
+            };
+
+            let waker = Waker::from(Rc::new(TaskWaker {
+                index,
+                inner: self.inner.clone(),
+            }));
+            let mut cx = Context::from_waker(&waker);
+
+            if fut.as_mut().poll(&mut cx).is_pending() {
+                self.inner.borrow_mut().tasks[index] = Some(fut);
+            } else {
+                self.inner.borrow_mut().free_tasks.push(index);
+            }
+        }
+    }
+
+    fn register(&self, build: impl FnOnce(u64) -> Entry) -> u64 {
+        let mut inner = self.inner.borrow_mut();
+        inner.next_user_data += 1;
+        let user_data = TAG | inner.next_user_data;
+        inner.completions.insert(user_data, State::Empty);
+        inner.to_submit.push_back(build(user_data));
+        user_data
+    }
+}
+
+struct TaskWaker {
+    index: usize,
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Rc<Self>) {
+        self.inner.borrow_mut().ready.push_back(self.index);
+    }
+
+    fn wake_by_ref(self: &Rc<Self>) {
+        self.inner.borrow_mut().ready.push_back(self.index);
+    }
+}
+
+/// A future resolving to `cqe.result()` once the op it wraps completes.
+pub struct Submission {
+    driver: Driver,
+    user_data: Option<u64>,
+    build: Option<Box<dyn FnOnce(u64) -> Entry>>,
+}
+
+impl Future for Submission {
+    type Output = io::Result<i32>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        let user_data = match this.user_data {
+            Some(user_data) => user_data,
+            None => {
+                let build = this.build.take().expect("Submission polled after registering");
+                let user_data = this.driver.register(build);
+                this.user_data = Some(user_data);
+                user_data
+            }
+        };
+
+        let mut inner = this.driver.inner.borrow_mut();
+        match inner.completions.get(&user_data) {
+            Some(State::Completed(_)) => {
+                match inner.completions.insert(user_data, State::Cancelled) {
+                    Some(State::Completed(result)) => Poll::Ready(result),
+                    _ => unreachable!(),
+                }
+            }
+            _ => {
+                inner
+                    .completions
+                    .insert(user_data, State::Submitted(cx.waker().clone()));
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Submission {
+    fn drop(&mut self) {
+        if let Some(user_data) = self.user_data {
+            self.driver.inner.borrow_mut().completions.remove(&user_data);
+        }
+    }
+}
+
+fn submit(driver: &Driver, build: impl FnOnce(u64) -> Entry + 'static) -> Submission {
+    Submission {
+        driver: driver.clone(),
+        user_data: None,
+        build: Some(Box::new(build)),
+    }
+}
+
+/// Await a plain `read(2)` through the ring.
+pub async fn read(driver: &Driver, fd: types::Fixed, buf: &mut [u8]) -> io::Result<i32> {
+    let ptr = buf.as_mut_ptr();
+    let len = buf.len() as u32;
+    submit(driver, move |ud| {
+        opcode::Read::new(fd, ptr, len).build().user_data(ud)
+    })
+    .await
+}
+
+/// Await a plain `recv(2)` through the ring.
+pub async fn recv(driver: &Driver, fd: types::Fixed, buf: &mut [u8]) -> io::Result<i32> {
+    let ptr = buf.as_mut_ptr();
+    let len = buf.len() as u32;
+    submit(driver, move |ud| {
+        opcode::Recv::new(fd, ptr, len).build().user_data(ud)
+    })
+    .await
+}
+
+/// Await a `sendmsg(2)` through the ring. `hdr` must outlive the returned
+/// future's resolution -- callers own that lifetime via a boxed state, the
+/// way `main.rs`'s `SendMsgState` does.
+pub async fn send_msg(driver: &Driver, fd: types::Fixed, hdr: *const libc::msghdr) -> io::Result<i32> {
+    let hdr = hdr as usize;
+    submit(driver, move |ud| {
+        opcode::SendMsg::new(fd, hdr as *const libc::msghdr)
+            .build()
+            .user_data(ud)
+    })
+    .await
+}
+
+/// Block the current thread on a single future, driving it with `pump`
+/// every time it returns `Pending`. `pump` is expected to be something
+/// like "submit and wait for at least one CQE, then hand every CQE to
+/// `Driver::complete` and call `Driver::run_ready`" -- i.e. one spin of the
+/// ring loop. Useful for callers (tests, tools) that just want to run one
+/// op to completion without reimplementing that loop themselves.
+pub fn block_on<F: Future>(mut fut: F, mut pump: impl FnMut() -> io::Result<()>) -> io::Result<F::Output> {
+    let waker = Waker::from(Rc::new(NoopWaker));
+    let mut cx = Context::from_waker(&waker);
+
+    // Safety: `fut` is owned by this stack frame and never moved out of it.
+    let mut fut = unsafe { Pin::new_unchecked(&mut fut) };
+    loop {
+        if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+            return Ok(out);
+        }
+        pump()?;
+    }
+}
+
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Rc<Self>) {}
+    fn wake_by_ref(self: &Rc<Self>) {}
+}
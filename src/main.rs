@@ -1,22 +1,33 @@
 use anyhow::{anyhow, Result};
 use clap::Parser;
 use io_uring::{
-    cqueue::CompletionQueue,
     opcode,
-    squeue::{self, Entry, SubmissionQueue},
+    squeue::{self, Entry},
     types, IoUring,
 };
-use std::str;
 
 use nix::{
     sched::{self, CpuSet},
     unistd::Pid,
 };
 use std::{
-    collections::vec_deque::VecDeque, fs::File, io::ErrorKind, net::UdpSocket,
-    os::unix::io::AsRawFd, thread,
+    collections::vec_deque::VecDeque,
+    fs::File,
+    io::{self, ErrorKind},
+    net::{SocketAddr, UdpSocket},
+    os::unix::io::AsRawFd,
+    thread,
 };
 
+mod block_io;
+mod bufring;
+mod executor;
+mod quic;
+
+use bufring::BufRing;
+use executor::Driver;
+use quic::QuicServer;
+
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -25,8 +36,6 @@ struct Opts {
     #[clap(short, long = "async")]
     async_work: bool,
     #[clap(short, long, default_value = "0")]
-    sqes: u32,
-    #[clap(short, long, default_value = "0")]
     max_unbounded_workers: u32,
     #[clap(short = 'r', long, default_value = "1")]
     num_rings: usize,
@@ -34,12 +43,47 @@ struct Opts {
     num_threads: usize,
     #[clap(short, long, default_value = "0")]
     cpu: Vec<usize>,
+    /// Size of each ring's completion queue. 0 leaves it at the kernel's
+    /// default (2x the SQ); networked workloads with many outstanding
+    /// multishot completions want real headroom here, e.g. 4x the SQ.
+    #[clap(long, default_value = "0")]
+    cq_size: u32,
+    /// Run each ring with an SQPOLL kernel thread instead of entering the
+    /// kernel via `submit()`/`submit_and_wait()` on every loop iteration.
+    /// Requires every fd the ring touches to go through the fixed-file
+    /// table `create_ring` already sets up.
+    #[clap(long)]
+    sqpoll: bool,
+    /// Milliseconds the SQPOLL thread spins before sleeping and needing an
+    /// explicit wakeup. Ignored unless `--sqpoll` is set.
+    #[clap(long, default_value = "1000")]
+    sqpoll_idle_ms: u32,
+    /// Pin the SQPOLL thread to this CPU. Ignored unless `--sqpoll` is set;
+    /// unset lets the kernel place it.
+    #[clap(long)]
+    sqpoll_cpu: Option<u32>,
+    /// Exercise the `O_DIRECT` block engine against this file instead of
+    /// running the QUIC server: write a block, read it back, and report
+    /// whether the round trip matched.
+    #[clap(long)]
+    block_device: Option<String>,
+}
+
+/// SQPOLL kernel-thread settings for `create_ring`, bundled together since
+/// they're only meaningful as a pair (and `create_ring` already has three
+/// scalar params before this one).
+struct SqPollConfig {
+    idle_ms: u32,
+    cpu: Option<u32>,
 }
 
 const MAX_SQES: u32 = 4096;
 
 fn main() -> Result<()> {
     let args = Opts::parse();
+    if let Some(path) = &args.block_device {
+        return run_block_engine_smoke_test(path);
+    }
     if args.num_threads > 1 {
         let threads: Vec<_> = (0..args.num_threads)
             .map(|_| thread::spawn(do_work))
@@ -53,15 +97,61 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Round-trip one block through `block_io::IoEngine` against `path`: write
+/// a known pattern to block 0, read it back, and report whether it
+/// matched. A quick way to exercise the `O_DIRECT` path without standing
+/// up a whole benchmark harness.
+fn run_block_engine_smoke_test(path: &str) -> Result<()> {
+    let mut engine = block_io::IoEngine::open(path, 8)?;
+
+    let mut out = block_io::Block::new(0);
+    out.as_mut_slice().fill(0xa5);
+    engine.write(&out)?;
+
+    let mut back = block_io::Block::new(0);
+    engine.read(&mut back)?;
+
+    if back.as_slice() == out.as_slice() {
+        println!("block_device round trip OK ({} bytes)", block_io::BLOCK_SIZE);
+    } else {
+        println!("block_device round trip MISMATCH");
+    }
+
+    Ok(())
+}
+
+/// `user_data` of the ring's single multishot `RecvMsgMulti`. Every other
+/// op on the ring goes through `executor::Driver`, whose `user_data`s all
+/// carry `executor::TAG`, so the two never collide.
+const RECV_MULTI_UD: u64 = 0;
+
+/// Buffer-group id and ring size for the provided buffers backing the
+/// multishot recv. 4096 buffers gives the kernel plenty of headroom before
+/// it has to terminate the multishot op for lack of a free buffer.
+const BUF_GROUP: u16 = 0;
+const BUF_RING_ENTRIES: u16 = 4096;
+const BUF_LEN: usize = 2048;
+
+/// Size of each ring's sparse fixed-file table and the slot the listening
+/// socket lives in. Sparse and bigger than we currently need so a future
+/// multi-socket version can register/rotate more listeners into the same
+/// table via `install_fixed_file` without re-registering the whole set.
+const FIXED_FILE_SLOTS: u32 = 16;
+const SINK_SLOT: u32 = 0;
+
+/// How many datagrams to process between `QuicServer::collect_garbage`
+/// sweeps. That sweep scans every connection, so it's kept off the
+/// per-packet hot path and run periodically instead.
+const GC_INTERVAL: u64 = 1024;
+
 fn do_work() -> Result<()> {
     let args = Opts::parse();
     println!("{:?}", thread::current().id());
     let sink = UdpSocket::bind("127.0.0.1:3000")?;
-    let sink_fd = types::Fd(sink.as_raw_fd());
+    let local_addr = sink.local_addr()?;
+    let sink_fd = types::Fixed(SINK_SLOT);
 
-    let mut backlog = VecDeque::new();
-    let mut rd_buf = [0u8; 1024];
-    let rd_op = opcode::Read::new(sink_fd, &mut rd_buf as _, rd_buf.len() as _);
+    let mut quic = QuicServer::new(local_addr)?;
 
     let rd_flags = if args.async_work {
         squeue::Flags::ASYNC
@@ -69,8 +159,6 @@ fn do_work() -> Result<()> {
         squeue::Flags::empty()
     };
 
-    let rd_sqe = rd_op.build().flags(rd_flags);
-
     let mut cpu_iter = args
         .cpu
         .iter()
@@ -81,40 +169,211 @@ fn do_work() -> Result<()> {
         })
         .cycle();
 
+    let sqpoll = args.sqpoll.then(|| SqPollConfig {
+        idle_ms: args.sqpoll_idle_ms,
+        cpu: args.sqpoll_cpu,
+    });
+
     let mut rings: Vec<IoUring> = Vec::with_capacity(args.num_rings);
     for _ in 0..args.num_rings {
-        rings.push(create_ring(MAX_SQES, args.max_unbounded_workers)?);
+        rings.push(create_ring(
+            MAX_SQES,
+            args.max_unbounded_workers,
+            args.cq_size,
+            sqpoll.as_ref(),
+        )?);
+    }
+
+    // Every ring gets its own sparse fixed-file table; install the bound
+    // socket into slot 0 of each so the hot path can address it with
+    // `types::Fixed` instead of paying a per-op fd lookup.
+    for r in &mut rings {
+        install_fixed_file(r, SINK_SLOT, sink.as_raw_fd())?;
     }
 
     let cpu_set = sched::sched_getaffinity(Pid::from_raw(0))?;
 
-    let num_sqes = if args.sqes > 0 { args.sqes } else { MAX_SQES };
+    // Each ring gets its own registered buffer ring, a backlog, an
+    // `executor::Driver` that owns every in-flight `SendMsg` as an
+    // awaitable task, and the `msghdr` template the multishot recv was
+    // armed with (namelen/controllen only; the kernel fills payload/name
+    // from the buffer ring, not from an `iovec` we control).
+    let mut buf_rings: Vec<BufRing> = Vec::with_capacity(args.num_rings);
+    let mut backlogs: Vec<VecDeque<Entry>> = Vec::with_capacity(args.num_rings);
+    let mut drivers: Vec<Driver> = Vec::with_capacity(args.num_rings);
+    let mut msghdr_templates: Vec<Box<libc::msghdr>> = Vec::with_capacity(args.num_rings);
+    // Cumulative `cq.overflow()` reading last observed per ring, so we only
+    // log and back off when the count actually grows.
+    let mut cq_overflows: Vec<u32> = vec![0; args.num_rings];
+    // Sticky per ring: true once the multishot recv has dearmed and is
+    // waiting to be re-posted. Only cleared once it's actually re-armed,
+    // so a ring backing off under cq overflow doesn't lose its rearm.
+    let mut recv_needs_rearm: Vec<bool> = vec![false; args.num_rings];
+    // Datagrams processed since the last `collect_garbage` sweep. That
+    // sweep is an O(n) `retain()` over every connection, so it's run every
+    // `GC_INTERVAL` packets instead of after each one.
+    let mut datagrams_since_gc: u64 = 0;
+
     for r in &mut rings {
         let c = cpu_iter.next().unwrap();
         sched::sched_setaffinity(Pid::from_raw(0), &c)?;
 
-        fill_sq(&mut r.submission(), &rd_sqe, num_sqes)?;
+        let buf_ring = BufRing::new(r, BUF_RING_ENTRIES, BUF_GROUP, BUF_LEN)?;
+
+        let mut template: Box<libc::msghdr> = Box::new(unsafe { std::mem::zeroed() });
+        template.msg_namelen = std::mem::size_of::<libc::sockaddr_storage>() as u32;
+
+        {
+            let mut sq = r.submission();
+            sq.sync();
+            let sqe = opcode::RecvMsgMulti::new(sink_fd, template.as_ref(), buf_ring.bgid())
+                .build()
+                .flags(rd_flags | squeue::Flags::BUFFER_SELECT)
+                .user_data(RECV_MULTI_UD);
+            unsafe {
+                sq.push(&sqe)?;
+            }
+            sq.sync();
+        }
+
+        buf_rings.push(buf_ring);
+        msghdr_templates.push(template);
+        backlogs.push(VecDeque::new());
+        drivers.push(Driver::new());
+
         r.submit()?;
     }
     sched::sched_setaffinity(Pid::from_raw(0), &cpu_set)?;
 
     loop {
-        for r in &mut rings {
+        for (ring_idx, r) in rings.iter_mut().enumerate() {
+            let buf_ring = &mut buf_rings[ring_idx];
+            let backlog = &mut backlogs[ring_idx];
+            let driver = &drivers[ring_idx];
+            let template = &msghdr_templates[ring_idx];
+
             let (submitter, mut sq, mut cq) = r.split();
-            match submitter.submit_and_wait(1) {
-                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
-                Err(e) => return Err(anyhow!(e)),
-                Ok(_) => (),
+            if args.sqpoll {
+                // The kernel poller submits on its own as long as it's
+                // awake; only pay for an enter syscall when
+                // IORING_SQ_NEED_WAKEUP says it's gone to sleep.
+                sq.sync();
+                if sq.need_wakeup() {
+                    submitter.submit()?;
+                }
+            } else {
+                match submitter.submit_and_wait(1) {
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(anyhow!(e)),
+                    Ok(_) => (),
+                }
             }
 
             cq.sync();
 
+            // If the kernel had nowhere to put a completion, it counts it
+            // here instead of silently dropping it. Growth since last time
+            // means we're producing CQEs faster than we're draining them,
+            // so back off admitting new work this round and let the next
+            // `submit_and_wait` catch the CQ up first.
+            let overflow_now = cq.overflow();
+            let overflowing = overflow_now > cq_overflows[ring_idx];
+            if overflowing {
+                println!(
+                    "ring {}: cq overflow, {} completions dropped so far",
+                    ring_idx, overflow_now
+                );
+                cq_overflows[ring_idx] = overflow_now;
+            }
+
+            let mut completed = Vec::new();
+            for cqe in &mut cq {
+                completed.push((cqe.user_data(), cqe.result(), cqe.flags()));
+            }
+
+            for (user_data, res, flags) in completed {
+                if user_data & executor::TAG != 0 {
+                    let result = if res < 0 {
+                        Err(io::Error::from_raw_os_error(-res))
+                    } else {
+                        Ok(res)
+                    };
+                    driver.complete(user_data, result);
+                    continue;
+                }
+
+                // The ring's single multishot RecvMsgMulti. IORING_CQE_F_MORE
+                // being clear means the kernel dearmed it (buffer exhaustion,
+                // an error, or cancellation), so only then do we re-post it.
+                if !io_uring::cqueue::more(flags) {
+                    recv_needs_rearm[ring_idx] = true;
+                }
+
+                if res < 0 {
+                    println!("recvmsg failed: {}", res);
+                    continue;
+                }
+
+                let Some(bid) = io_uring::cqueue::buffer_select(flags) else {
+                    println!("recvmsg completion carried no buffer id");
+                    continue;
+                };
+
+                let payload = buf_ring.payload_mut(bid, res as usize);
+                match parse_recvmsg_multi(payload, template) {
+                    Ok((from, data)) => {
+                        if let Err(e) = handle_datagram(&mut quic, from, data, driver, sink_fd) {
+                            println!("datagram handling error: {:?}", e);
+                        }
+                        datagrams_since_gc += 1;
+                        if datagrams_since_gc >= GC_INTERVAL {
+                            quic.collect_garbage();
+                            datagrams_since_gc = 0;
+                        }
+                    }
+                    Err(e) => println!("malformed multishot recvmsg completion: {:?}", e),
+                }
+
+                buf_ring.recycle(bid);
+            }
+
+            driver.run_ready();
+
+            // Back off admitting new work while the CQ is overflowing: both
+            // re-arming the multishot recv and flushing new SendMsgs would
+            // only produce more completions for a queue that's already
+            // dropping them.
+            if !overflowing {
+                if recv_needs_rearm[ring_idx] {
+                    let sqe =
+                        opcode::RecvMsgMulti::new(sink_fd, template.as_ref(), buf_ring.bgid())
+                            .build()
+                            .flags(rd_flags | squeue::Flags::BUFFER_SELECT)
+                            .user_data(RECV_MULTI_UD);
+                    backlog.push_back(sqe);
+                    recv_needs_rearm[ring_idx] = false;
+                }
+
+                backlog.extend(driver.drain_submissions());
+            }
+
             loop {
                 if sq.is_full() {
-                    match submitter.submit() {
-                        Ok(_) => (),
-                        Err(ref err) if err.raw_os_error() == Some(libc::EBUSY) => break,
-                        Err(err) => return Err(err.into()),
+                    if args.sqpoll {
+                        // Same wakeup gating as above: the poller drains
+                        // the SQ on its own unless it's asleep.
+                        if sq.need_wakeup() {
+                            submitter.submit()?;
+                        }
+                        if sq.is_full() {
+                            break;
+                        }
+                    } else {
+                        match submitter.submit() {
+                            Ok(_) => (),
+                            Err(ref err) if err.raw_os_error() == Some(libc::EBUSY) => break,
+                            Err(err) => return Err(err.into()),
+                        }
                     }
                 }
                 sq.sync();
@@ -126,51 +385,218 @@ fn do_work() -> Result<()> {
                 }
             }
 
-            fill_sq(&mut sq, &rd_sqe, num_sqes)?;
+            // New work was just queued; wake the poller if it's asleep so
+            // it doesn't sit on a full backlog until something else kicks
+            // it (e.g. the next ring's own traffic).
+            if args.sqpoll && sq.need_wakeup() {
+                submitter.submit()?;
+            }
+        }
+    }
+}
 
-            for cqe in &mut cq {
-                let res = cqe.result();
-                let index = cqe.user_data() as usize;
+/// Hand a just-completed datagram to the QUIC server, then spawn one
+/// awaited `SendMsg` task per outgoing packet `quiche` wants sent back to
+/// `from`.
+fn handle_datagram(
+    quic: &mut QuicServer,
+    from: SocketAddr,
+    data: &mut [u8],
+    driver: &Driver,
+    fd: types::Fixed,
+) -> Result<()> {
+    let conn = match quic.recv(data, from)? {
+        quic::RecvOutcome::Retry(packet) => {
+            send_packet(driver, fd, from, &packet);
+            return Ok(());
+        }
+        quic::RecvOutcome::Connection(conn) => conn,
+    };
 
-                let cur = str::from_utf8(&rd_buf[..res as usize])?;
-                println!("{:?}, {:?}", cur, index);
+    loop {
+        let mut out = SendMsgState::new(from);
+        match conn.send(&mut out.buf) {
+            Ok((written, _send_info)) => {
+                out.len = written;
             }
+            Err(quiche::Error::Done) => break,
+            Err(e) => return Err(anyhow!(e)),
         }
+
+        let task_driver = driver.clone();
+        driver.spawn(async move {
+            if let Err(e) = executor::send_msg(&task_driver, fd, out.prepare()).await {
+                println!("sendmsg failed: {:?}", e);
+            }
+        });
     }
-}
 
-fn create_ring(max_sqes: u32, max_unbounded_workers: u32) -> Result<IoUring> {
-    let ring = IoUring::new(max_sqes)?;
-    let sub = ring.submitter();
+    Ok(())
+}
 
-    let mut max_workers: [u32; 2] = [0, max_unbounded_workers];
-    sub.register_iowq_max_workers(&mut max_workers)?;
+/// Spawn an awaited `SendMsg` for a datagram `quic::QuicServer` produced
+/// outside the usual `quiche::Connection::send` loop (e.g. a Retry),
+/// copying `payload` into a fresh `SendMsgState` the same way every other
+/// outgoing packet is sent.
+fn send_packet(driver: &Driver, fd: types::Fixed, peer: SocketAddr, payload: &[u8]) {
+    let mut out = SendMsgState::new(peer);
+    out.buf[..payload.len()].copy_from_slice(payload);
+    out.len = payload.len();
 
-    Ok(ring)
+    let task_driver = driver.clone();
+    driver.spawn(async move {
+        if let Err(e) = executor::send_msg(&task_driver, fd, out.prepare()).await {
+            println!("sendmsg failed: {:?}", e);
+        }
+    });
 }
 
-fn fill_sq(sq: &mut SubmissionQueue, sqe: &Entry, num_sqes: u32) -> Result<()> {
-    let mut i = 0;
+/// Pull the source address and payload out of one multishot `RecvMsgMulti`
+/// completion buffer. `template` must be the same `msghdr` the SQE was
+/// armed with -- `RecvMsgOut::parse` needs its `msg_namelen`/`msg_controllen`
+/// to know how much space the kernel reserved for the name/control sections,
+/// which is not the same as the name/control lengths it actually reports.
+fn parse_recvmsg_multi<'a>(
+    buf: &'a mut [u8],
+    template: &libc::msghdr,
+) -> Result<(SocketAddr, &'a mut [u8])> {
+    let buf_ptr = buf.as_ptr();
+    let (from, payload_off, payload_len) = {
+        let parsed = types::RecvMsgOut::parse(buf, template)
+            .map_err(|_| anyhow!("multishot recvmsg buffer shorter than its header"))?;
 
-    sq.sync();
-    while !sq.is_full() && i < num_sqes {
+        let name = parsed.name_data();
+        let name_len = name.len().min(std::mem::size_of::<libc::sockaddr_storage>());
+        let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
         unsafe {
-            sq.push(sqe)?;
+            std::ptr::copy_nonoverlapping(name.as_ptr(), &mut storage as *mut _ as *mut u8, name_len);
+        }
+        let from = sockaddr_storage_to_socket_addr(&storage)?;
+
+        let payload = parsed.payload_data();
+        let payload_off = payload.as_ptr() as usize - buf_ptr as usize;
+        (from, payload_off, payload.len())
+    };
+
+    Ok((from, &mut buf[payload_off..payload_off + payload_len]))
+}
+
+/// State for an in-flight `SendMsg`: the outgoing payload quiche wrote into
+/// `buf`, addressed at `peer` via an `iovec`/`msghdr` pair. Owned by the
+/// async task spawned in `handle_datagram` for as long as the `SendMsg` is
+/// in flight, which is what keeps its buffer alive for the kernel.
+///
+/// Boxed so its address is stable; `hdr`'s pointers to `peer` and `iov`
+/// are fixed up once, right after allocation, and `prepare` points `iov`
+/// at the written payload once its length is known.
+struct SendMsgState {
+    buf: [u8; quic::MAX_DATAGRAM_SIZE],
+    len: usize,
+    peer: libc::sockaddr_storage,
+    peer_len: libc::socklen_t,
+    iov: libc::iovec,
+    hdr: libc::msghdr,
+}
+
+impl SendMsgState {
+    fn new(peer: SocketAddr) -> Box<Self> {
+        let (peer_storage, peer_len) = socket_addr_to_sockaddr_storage(peer);
+        let mut state = Box::new(SendMsgState {
+            buf: [0u8; quic::MAX_DATAGRAM_SIZE],
+            len: 0,
+            peer: peer_storage,
+            peer_len,
+            iov: unsafe { std::mem::zeroed() },
+            hdr: unsafe { std::mem::zeroed() },
+        });
+
+        state.hdr.msg_name = &mut state.peer as *mut _ as *mut _;
+        state.hdr.msg_namelen = state.peer_len;
+        state.hdr.msg_iov = &mut state.iov as *mut _;
+        state.hdr.msg_iovlen = 1;
+
+        state
+    }
+
+    /// Finish wiring up `iov` to the written payload and return the
+    /// `msghdr` pointer `executor::send_msg` submits.
+    fn prepare(&mut self) -> *const libc::msghdr {
+        self.iov.iov_base = self.buf.as_mut_ptr() as *mut _;
+        self.iov.iov_len = self.len;
+        &self.hdr
+    }
+}
+
+fn sockaddr_storage_to_socket_addr(storage: &libc::sockaddr_storage) -> Result<SocketAddr> {
+    match storage.ss_family as i32 {
+        libc::AF_INET => {
+            let addr: libc::sockaddr_in =
+                unsafe { *(storage as *const _ as *const libc::sockaddr_in) };
+            let ip = std::net::Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+            Ok(SocketAddr::from((ip, u16::from_be(addr.sin_port))))
         }
-        i += 1;
+        libc::AF_INET6 => {
+            let addr: libc::sockaddr_in6 =
+                unsafe { *(storage as *const _ as *const libc::sockaddr_in6) };
+            let ip = std::net::Ipv6Addr::from(addr.sin6_addr.s6_addr);
+            Ok(SocketAddr::from((ip, u16::from_be(addr.sin6_port))))
+        }
+        family => Err(anyhow!("unsupported sockaddr family {}", family)),
     }
-    sq.sync();
+}
 
-    Ok(())
+fn socket_addr_to_sockaddr_storage(addr: SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let len = match addr {
+        SocketAddr::V4(v4) => {
+            let sin = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = v4.port().to_be();
+            sin.sin_addr.s_addr = u32::from(*v4.ip()).to_be();
+            std::mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(v6) => {
+            let sin6 = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = v6.port().to_be();
+            sin6.sin6_addr.s6_addr = v6.ip().octets();
+            std::mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+    (storage, len as libc::socklen_t)
 }
 
-fn drain_cq(cq: &mut CompletionQueue, rd_buf: &mut [u8; 1024]) -> Result<()> {
-    cq.sync();
-    for r in cq.into_iter() {
-        let cur = str::from_utf8(&rd_buf[0..r.result() as usize])?;
-        println!("{:?}", cur);
+fn create_ring(
+    max_sqes: u32,
+    max_unbounded_workers: u32,
+    cq_size: u32,
+    sqpoll: Option<&SqPollConfig>,
+) -> Result<IoUring> {
+    let mut builder = IoUring::builder();
+    if cq_size > 0 {
+        builder.setup_cqsize(cq_size);
+    }
+    if let Some(sqpoll) = sqpoll {
+        builder.setup_sqpoll(sqpoll.idle_ms);
+        if let Some(cpu) = sqpoll.cpu {
+            builder.setup_sqpoll_cpu(cpu);
+        }
     }
-    cq.sync();
+    let ring = builder.build(max_sqes)?;
+    let sub = ring.submitter();
+
+    let mut max_workers: [u32; 2] = [0, max_unbounded_workers];
+    sub.register_iowq_max_workers(&mut max_workers)?;
+
+    sub.register_files_sparse(FIXED_FILE_SLOTS)?;
+
+    Ok(ring)
+}
+
+/// Install (or replace) `fd` at fixed-file `slot`, updating only that slot
+/// instead of re-registering the ring's whole fixed-file table.
+fn install_fixed_file(io_uring: &IoUring, slot: u32, fd: std::os::unix::io::RawFd) -> Result<()> {
+    io_uring.submitter().register_files_update(slot, &[fd])?;
     Ok(())
 }
 
@@ -179,9 +605,13 @@ fn default_args() {
     let args = Opts::parse();
     println!("{:?}", args);
     assert_eq!(args.async_work, false);
-    assert_eq!(args.sqes, 0);
     assert_eq!(args.max_unbounded_workers, 0);
     assert_eq!(args.num_rings, 1);
     assert_eq!(args.num_threads, 1);
     assert_eq!(args.cpu, vec![0]);
+    assert_eq!(args.cq_size, 0);
+    assert_eq!(args.sqpoll, false);
+    assert_eq!(args.sqpoll_idle_ms, 1000);
+    assert_eq!(args.sqpoll_cpu, None);
+    assert_eq!(args.block_device, None);
 }
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::{anyhow, Result};
+use ring::hmac;
+
+/// Maximum size of a single QUIC datagram we'll read or write.
+///
+/// 1350 keeps us comfortably under the common-case path MTU (1500) once
+/// IP/UDP headers are accounted for, which is what quiche's own examples
+/// use for a listener that doesn't do PMTU discovery.
+pub const MAX_DATAGRAM_SIZE: usize = 1350;
+
+/// Owns every `quiche::Connection` the listener is driving, keyed by the
+/// client's UDP source address.
+///
+/// io_uring hands us raw datagrams with no notion of "connection" attached,
+/// so we rebuild that mapping ourselves: the peer `sockaddr` recovered from
+/// each `RecvMsg` completion is the only handle we have back to the
+/// `quiche::Connection` that owns a given client's handshake and streams.
+pub struct QuicServer {
+    config: quiche::Config,
+    local_addr: SocketAddr,
+    conns: HashMap<SocketAddr, quiche::Connection>,
+    /// Keys the HMAC tag minted into every Retry token, so a token can only
+    /// be forged by something that already holds this process's memory --
+    /// not just by guessing a peer's claimed source address.
+    token_key: hmac::Key,
+}
+
+/// Result of feeding one datagram to [`QuicServer::recv`].
+pub enum RecvOutcome<'a> {
+    /// The datagram belongs to an (already or newly) accepted connection,
+    /// which should now be drained via `Connection::send`.
+    Connection(&'a mut quiche::Connection),
+    /// The peer hasn't proven it owns its claimed source address yet; send
+    /// this Retry packet back to it instead of accepting a connection.
+    Retry(Vec<u8>),
+}
+
+fn addr_bytes(ip: IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(v4) => v4.octets().to_vec(),
+        IpAddr::V6(v6) => v6.octets().to_vec(),
+    }
+}
+
+impl QuicServer {
+    pub fn new(local_addr: SocketAddr) -> Result<Self> {
+        let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION)?;
+        config.set_application_protos(&[b"hq-interop"])?;
+        config.set_max_idle_timeout(30_000);
+        config.set_max_recv_udp_payload_size(MAX_DATAGRAM_SIZE);
+        config.set_max_send_udp_payload_size(MAX_DATAGRAM_SIZE);
+        config.set_initial_max_data(10_000_000);
+        config.set_initial_max_stream_data_bidi_local(1_000_000);
+        config.set_initial_max_stream_data_bidi_remote(1_000_000);
+        config.set_initial_max_streams_bidi(100);
+
+        Ok(Self {
+            config,
+            local_addr,
+            conns: HashMap::new(),
+            token_key: Self::new_token_key(),
+        })
+    }
+
+    /// Feed one datagram from `from` into the connection it belongs to,
+    /// accepting a brand new `quiche::Connection` on first contact -- unless
+    /// that first contact doesn't carry a validated address token yet, in
+    /// which case we hand back a Retry packet instead of allocating one.
+    pub fn recv(&mut self, buf: &mut [u8], from: SocketAddr) -> Result<RecvOutcome<'_>> {
+        if !self.conns.contains_key(&from) {
+            let hdr = quiche::Header::from_slice(buf, quiche::MAX_CONN_ID_LEN)?;
+
+            if hdr.ty != quiche::Type::Initial {
+                return Err(anyhow!(
+                    "first packet from a new peer must be Initial, got {:?}",
+                    hdr.ty
+                ));
+            }
+
+            let odcid = match hdr.token.as_deref() {
+                Some(token) if !token.is_empty() => self
+                    .validate_token(token, from)
+                    .ok_or_else(|| anyhow!("invalid address validation token from {}", from))?,
+                _ => {
+                    // No token yet: mint one and ask the peer to retry
+                    // instead of allocating a Connection for what could be
+                    // a spoofed source address (the classic QUIC
+                    // amplification gap Retry exists to close).
+                    let new_scid = Self::new_scid();
+                    let token = self.mint_token(&hdr, from);
+                    let mut retry_buf = [0u8; MAX_DATAGRAM_SIZE];
+                    let written = quiche::retry(
+                        &hdr.scid,
+                        &hdr.dcid,
+                        &new_scid,
+                        &token,
+                        hdr.version,
+                        &mut retry_buf,
+                    )?;
+                    return Ok(RecvOutcome::Retry(retry_buf[..written].to_vec()));
+                }
+            };
+
+            let scid = Self::new_scid();
+            let conn = quiche::accept(
+                &scid,
+                Some(&quiche::ConnectionId::from_vec(odcid)),
+                self.local_addr,
+                from,
+                &mut self.config,
+            )?;
+            self.conns.insert(from, conn);
+        }
+
+        let conn = self
+            .conns
+            .get_mut(&from)
+            .expect("connection was just accepted or already present");
+        let recv_info = quiche::RecvInfo {
+            from,
+            to: self.local_addr,
+        };
+        conn.recv(buf, recv_info)?;
+        Ok(RecvOutcome::Connection(conn))
+    }
+
+    /// Encode the original destination connection ID a Retry was minted for
+    /// into a token tied to `src` and signed with `token_key`, so
+    /// `validate_token` can tell a real roundtrip apart from an attacker
+    /// forging a token for a source address it doesn't control.
+    fn mint_token(&self, hdr: &quiche::Header, src: SocketAddr) -> Vec<u8> {
+        let odcid = hdr.dcid.as_ref();
+        let tag = hmac::sign(&self.token_key, &Self::token_signed_payload(odcid, src));
+
+        let mut token = Vec::new();
+        token.extend_from_slice(b"quiche");
+        token.extend_from_slice(tag.as_ref());
+        token.extend_from_slice(odcid);
+        token
+    }
+
+    /// Recover the original destination connection ID from a token minted
+    /// by `mint_token`, rejecting it unless its HMAC tag verifies against
+    /// `token_key` for `src`.
+    fn validate_token(&self, token: &[u8], src: SocketAddr) -> Option<Vec<u8>> {
+        let token = token.strip_prefix(b"quiche")?;
+        if token.len() < ring::digest::SHA256_OUTPUT_LEN {
+            return None;
+        }
+        let (tag, odcid) = token.split_at(ring::digest::SHA256_OUTPUT_LEN);
+        hmac::verify(&self.token_key, &Self::token_signed_payload(odcid, src), tag).ok()?;
+        Some(odcid.to_vec())
+    }
+
+    /// Bytes the token's HMAC tag is computed over: the original
+    /// destination connection ID plus the claimed source address, so a tag
+    /// minted for one peer or one connection attempt can't be replayed for
+    /// another.
+    fn token_signed_payload(odcid: &[u8], src: SocketAddr) -> Vec<u8> {
+        let mut payload = addr_bytes(src.ip());
+        payload.extend_from_slice(&src.port().to_be_bytes());
+        payload.extend_from_slice(odcid);
+        payload
+    }
+
+    fn new_token_key() -> hmac::Key {
+        use ring::rand::SecureRandom;
+        let mut key_bytes = [0u8; 32];
+        ring::rand::SystemRandom::new()
+            .fill(&mut key_bytes)
+            .expect("system randomness source is unavailable");
+        hmac::Key::new(hmac::HMAC_SHA256, &key_bytes)
+    }
+
+    pub fn conn_mut(&mut self, peer: &SocketAddr) -> Option<&mut quiche::Connection> {
+        self.conns.get_mut(peer)
+    }
+
+    /// Every peer address with a connection in the map, including ones that
+    /// have closed but not yet been dropped by `collect_garbage`.
+    pub fn peers(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.conns.keys().copied()
+    }
+
+    /// Drop connections that finished closing so the map doesn't grow
+    /// without bound over the life of the listener.
+    pub fn collect_garbage(&mut self) {
+        self.conns.retain(|_, c| !c.is_closed());
+    }
+
+    fn new_scid() -> quiche::ConnectionId<'static> {
+        use ring::rand::SecureRandom;
+        let mut id = [0u8; quiche::MAX_CONN_ID_LEN];
+        ring::rand::SystemRandom::new()
+            .fill(&mut id)
+            .expect("system randomness source is unavailable");
+        quiche::ConnectionId::from_vec(id.to_vec())
+    }
+}